@@ -0,0 +1,520 @@
+//! Export/import of the whole vault as a single encrypted backup archive,
+//! for moving data between devices or restoring after data loss. Built on
+//! top of the same [`crate::crypto`] primitives used for at-rest encryption,
+//! but under an independent passphrase-derived key rather than the unlocked
+//! vault session.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto;
+
+const ARCHIVE_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct VaultArchive {
+    version: u8,
+    /// Base64-encoded contents of `.vault-salt`, if the vault has been
+    /// unlocked at least once. Without this, restoring onto a fresh
+    /// `app_data_dir` would derive a brand-new key on the next `unlock()`
+    /// that doesn't match the imported ciphertexts.
+    vault_salt: Option<String>,
+    entries: BTreeMap<String, String>,
+}
+
+/// Key used in the returned report for the bundled vault salt, so callers
+/// can tell whether it was restored alongside the regular entries.
+pub const VAULT_SALT_REPORT_KEY: &str = crypto::VAULT_SALT_FILE;
+
+/// How to handle a storage key that already exists on disk during import.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing entry with the imported one.
+    Overwrite,
+    /// Leave the existing entry untouched.
+    SkipExisting,
+    /// Shallow-merge the two as JSON objects, keeping existing values on key
+    /// conflicts and filling in anything only present in the import. Only
+    /// supported for plaintext entries: a conflicting key where either side
+    /// is encrypted fails the import rather than merging ciphertext fields.
+    /// Keys not already present are written as with `Overwrite`.
+    Merge,
+}
+
+impl ConflictPolicy {
+    pub fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "skip-existing" => Ok(ConflictPolicy::SkipExisting),
+            "merge" => Ok(ConflictPolicy::Merge),
+            other => Err(format!("unknown conflict policy '{}'", other)),
+        }
+    }
+}
+
+/// What happened (or, in dry-run mode, would happen) to a single storage key
+/// during import.
+#[derive(Serialize)]
+pub struct ImportEntryReport {
+    pub key: String,
+    pub action: ImportAction,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportAction {
+    Written,
+    Skipped,
+    Merged,
+    Unchanged,
+}
+
+/// Bundles every `*.json` entry in `app_data_dir`, plus the vault's Argon2
+/// salt (so the backup can be decrypted with the master password again
+/// after a restore), into a single archive encrypted under a key derived
+/// from `passphrase`. Returns the serialized, self-describing backup blob.
+pub fn export_vault(app_data_dir: &Path, passphrase: &str) -> Result<String, String> {
+    let mut entries = BTreeMap::new();
+    let mut vault_salt = None;
+
+    if app_data_dir.exists() {
+        for entry in fs::read_dir(app_data_dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let contents = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            entries.insert(stem.to_string(), contents);
+        }
+
+        let salt_path = app_data_dir.join(crypto::VAULT_SALT_FILE);
+        if salt_path.exists() {
+            let raw = fs::read(&salt_path).map_err(|e| e.to_string())?;
+            vault_salt = Some(base64::encode(raw));
+        }
+    }
+
+    let archive = VaultArchive {
+        version: ARCHIVE_VERSION,
+        vault_salt,
+        entries,
+    };
+    let serialized = serde_json::to_vec(&archive).map_err(|e| e.to_string())?;
+
+    crypto::encrypt_with_passphrase(passphrase, &serialized)
+}
+
+/// Decrypts `data` with a key derived from `passphrase`, validates the
+/// archive version, then validates every entry (read via `read_existing`)
+/// and the vault salt (read via `read_existing_salt`) against `policy`
+/// *before* writing anything. Only once the whole archive is known to be
+/// importable does it restore the salt via `write_salt` and write each entry
+/// back through `write_entry`. This way a
+/// policy violation partway through the archive (e.g. `Merge` hitting an
+/// already-encrypted entry) fails the whole import without having mutated
+/// the vault. In `dry_run` mode neither `write_salt` nor `write_entry` is
+/// ever called, and the report describes what would happen instead.
+pub fn import_vault(
+    data: &str,
+    passphrase: &str,
+    policy: ConflictPolicy,
+    dry_run: bool,
+    mut read_existing: impl FnMut(&str) -> Option<String>,
+    mut write_entry: impl FnMut(&str, &str) -> Result<(), String>,
+    read_existing_salt: impl FnOnce() -> Option<Vec<u8>>,
+    write_salt: impl FnOnce(&[u8]) -> Result<(), String>,
+) -> Result<Vec<ImportEntryReport>, String> {
+    if !crypto::is_encrypted(data) {
+        return Err("backup data is not a recognized encrypted vault archive".to_string());
+    }
+
+    let plaintext = crypto::decrypt_with_passphrase(passphrase, data)?;
+
+    let archive: VaultArchive = serde_json::from_slice(&plaintext)
+        .map_err(|_| "decrypted archive is malformed".to_string())?;
+
+    if archive.version != ARCHIVE_VERSION {
+        return Err(format!(
+            "unsupported backup archive version {}",
+            archive.version
+        ));
+    }
+
+    // Validation pass: resolve what should happen to the salt and to every
+    // entry without touching disk. Any policy violation (e.g. `Merge`
+    // against an already-encrypted entry) returns here, before the write
+    // pass below has had a chance to mutate anything.
+    let salt_plan = if let Some(salt_b64) = &archive.vault_salt {
+        let imported_salt = base64::decode(salt_b64)
+            .map_err(|_| "backup's vault salt is malformed".to_string())?;
+        let existing_salt = read_existing_salt();
+
+        let (action, final_salt) = match (&existing_salt, policy) {
+            (None, _) => (ImportAction::Written, Some(imported_salt)),
+            (Some(_), ConflictPolicy::Overwrite) => (ImportAction::Written, Some(imported_salt)),
+            (Some(_), ConflictPolicy::SkipExisting) => (ImportAction::Skipped, None),
+            (Some(existing), ConflictPolicy::Merge) => {
+                if *existing == imported_salt {
+                    (ImportAction::Unchanged, None)
+                } else {
+                    return Err(
+                        "cannot merge vaults derived from different master-password salts; \
+                         use \"overwrite\" or \"skip-existing\" instead"
+                            .to_string(),
+                    );
+                }
+            }
+        };
+
+        Some((action, final_salt))
+    } else {
+        None
+    };
+
+    let mut entry_plans = Vec::with_capacity(archive.entries.len());
+    for (key, imported_contents) in &archive.entries {
+        let existing = read_existing(key);
+
+        let (action, final_contents) = match (&existing, policy) {
+            (None, _) => (ImportAction::Written, Some(imported_contents.clone())),
+            (Some(_), ConflictPolicy::Overwrite) => {
+                (ImportAction::Written, Some(imported_contents.clone()))
+            }
+            (Some(_), ConflictPolicy::SkipExisting) => (ImportAction::Skipped, None),
+            (Some(existing), ConflictPolicy::Merge) => {
+                // Once a vault has been unlocked, every entry is an opaque
+                // `EncryptedEnvelope` JSON object, so a shallow field merge
+                // would silently "succeed" while mixing ciphertext fields
+                // from two unrelated entries. Merge is only meaningful for
+                // plaintext entries that predate encryption.
+                if crypto::is_encrypted(existing) || crypto::is_encrypted(imported_contents) {
+                    return Err(format!(
+                        "cannot merge key '{}': merge is only supported for plaintext entries; \
+                         encrypted vaults must use \"overwrite\" or \"skip-existing\"",
+                        key
+                    ));
+                }
+                match merge_json(existing, imported_contents) {
+                    Some(merged) if merged != *existing => (ImportAction::Merged, Some(merged)),
+                    _ => (ImportAction::Unchanged, None),
+                }
+            }
+        };
+
+        entry_plans.push((key.clone(), action, final_contents));
+    }
+
+    // Write pass: the whole archive validated cleanly, so it's now safe to
+    // actually touch disk (skipped entirely in dry-run mode).
+    let mut report = Vec::with_capacity(entry_plans.len() + 1);
+
+    if let Some((action, final_salt)) = salt_plan {
+        if !dry_run {
+            if let Some(salt) = &final_salt {
+                write_salt(salt)?;
+            }
+        }
+        report.push(ImportEntryReport {
+            key: VAULT_SALT_REPORT_KEY.to_string(),
+            action,
+        });
+    }
+
+    for (key, action, final_contents) in entry_plans {
+        if !dry_run {
+            if let Some(contents) = &final_contents {
+                write_entry(&key, contents)?;
+            }
+        }
+        report.push(ImportEntryReport { key, action });
+    }
+
+    Ok(report)
+}
+
+/// Shallow-merges two JSON objects, keeping `existing`'s values on key
+/// conflicts and adding anything only present in `imported`. Returns `None`
+/// if either side isn't a JSON object, in which case the caller should treat
+/// the entry as unchanged rather than guess at a merge.
+fn merge_json(existing: &str, imported: &str) -> Option<String> {
+    let existing_value: serde_json::Value = serde_json::from_str(existing).ok()?;
+    let imported_value: serde_json::Value = serde_json::from_str(imported).ok()?;
+
+    let (serde_json::Value::Object(mut existing_map), serde_json::Value::Object(imported_map)) =
+        (existing_value, imported_value)
+    else {
+        return None;
+    };
+
+    for (k, v) in imported_map {
+        existing_map.entry(k).or_insert(v);
+    }
+
+    serde_json::to_string(&serde_json::Value::Object(existing_map)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("cipherguard-backup-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Seeds `dir` the way an unlocked vault actually looks on disk: a
+    /// `.vault-salt` file plus each entry written as a real
+    /// `crypto::encrypt`-produced envelope, rather than raw plaintext JSON.
+    fn seed_real_vault(dir: &std::path::Path, master_password: &str, entries: &[(&str, &str)]) {
+        let salt = crypto::random_salt();
+        fs::write(dir.join(crypto::VAULT_SALT_FILE), salt).unwrap();
+
+        let key = crypto::derive_key(master_password, &salt).unwrap();
+        for (name, plaintext) in entries {
+            let envelope = crypto::encrypt(&key, &salt, plaintext.as_bytes()).unwrap();
+            fs::write(dir.join(format!("{}.json", name)), envelope).unwrap();
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn import_with_fs(
+        archive: &str,
+        passphrase: &str,
+        policy: ConflictPolicy,
+        dry_run: bool,
+        existing: &RefCell<HashMap<String, String>>,
+        existing_salt: &RefCell<Option<Vec<u8>>>,
+    ) -> Result<Vec<ImportEntryReport>, String> {
+        import_vault(
+            archive,
+            passphrase,
+            policy,
+            dry_run,
+            |key| existing.borrow().get(key).cloned(),
+            |key, contents| {
+                existing.borrow_mut().insert(key.to_string(), contents.to_string());
+                Ok(())
+            },
+            || existing_salt.borrow().clone(),
+            |salt| {
+                *existing_salt.borrow_mut() = Some(salt.to_vec());
+                Ok(())
+            },
+        )
+    }
+
+    #[test]
+    fn export_then_import_round_trips_a_real_encrypted_vault() {
+        let dir = temp_dir("real-roundtrip-1");
+        seed_real_vault(
+            &dir,
+            "master-password",
+            &[("accounts", "{\"user\":\"alice\"}"), ("notes", "{\"body\":\"hi\"}")],
+        );
+        let original_salt = fs::read(dir.join(crypto::VAULT_SALT_FILE)).unwrap();
+
+        let archive = export_vault(&dir, "backup-pass").unwrap();
+
+        // Restoring onto a completely empty app data dir, as if on a new device.
+        let written = RefCell::new(HashMap::new());
+        let restored_salt = RefCell::new(None);
+        let report = import_with_fs(
+            &archive,
+            "backup-pass",
+            ConflictPolicy::Overwrite,
+            false,
+            &written,
+            &restored_salt,
+        )
+        .unwrap();
+
+        // One report entry per vault entry, plus the restored salt.
+        assert_eq!(report.len(), 3);
+        assert_eq!(restored_salt.borrow().as_deref(), Some(original_salt.as_slice()));
+
+        // The restored salt must actually unlock the restored ciphertexts
+        // with the original master password, proving this isn't just a
+        // byte-identical copy that happens to decrypt under the export step.
+        let key = crypto::derive_key("master-password", &original_salt.try_into().unwrap()).unwrap();
+        let restored_accounts = written.borrow().get("accounts").unwrap().clone();
+        assert!(crypto::is_encrypted(&restored_accounts));
+        let plaintext = crypto::decrypt(&key, &restored_accounts).unwrap();
+        assert_eq!(plaintext, b"{\"user\":\"alice\"}");
+    }
+
+    #[test]
+    fn import_fails_with_wrong_passphrase() {
+        let dir = temp_dir("wrong-pass-1");
+        fs::write(dir.join("accounts.json"), "{\"user\":\"alice\"}").unwrap();
+        let archive = export_vault(&dir, "correct-pass").unwrap();
+
+        let written = RefCell::new(HashMap::new());
+        let restored_salt = RefCell::new(None);
+        let result = import_with_fs(
+            &archive,
+            "incorrect-pass",
+            ConflictPolicy::Overwrite,
+            false,
+            &written,
+            &restored_salt,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn skip_existing_leaves_conflicting_entries_and_salt_untouched() {
+        let dir = temp_dir("skip-1");
+        seed_real_vault(&dir, "master-password", &[("accounts", "{\"user\":\"alice\"}")]);
+        let archive = export_vault(&dir, "pass").unwrap();
+        let imported_salt = fs::read(dir.join(crypto::VAULT_SALT_FILE)).unwrap();
+
+        let existing = RefCell::new(HashMap::from([(
+            "accounts".to_string(),
+            "{\"user\":\"bob\"}".to_string(),
+        )]));
+        let existing_salt = RefCell::new(Some(vec![0xAB; crypto::SALT_LEN]));
+
+        let report = import_with_fs(
+            &archive,
+            "pass",
+            ConflictPolicy::SkipExisting,
+            false,
+            &existing,
+            &existing_salt,
+        )
+        .unwrap();
+
+        assert_eq!(existing.borrow().get("accounts").unwrap(), "{\"user\":\"bob\"}");
+        assert_ne!(existing_salt.borrow().as_ref().unwrap(), &imported_salt);
+        assert!(report.iter().all(|r| r.action == ImportAction::Skipped));
+    }
+
+    #[test]
+    fn merge_fills_in_missing_fields_for_plaintext_entries() {
+        let dir = temp_dir("merge-1");
+        fs::write(dir.join("accounts.json"), "{\"user\":\"alice\",\"extra\":\"new\"}").unwrap();
+        let archive = export_vault(&dir, "pass").unwrap();
+
+        let existing = RefCell::new(HashMap::from([(
+            "accounts".to_string(),
+            "{\"user\":\"bob\"}".to_string(),
+        )]));
+        let existing_salt = RefCell::new(None);
+
+        let report = import_with_fs(
+            &archive,
+            "pass",
+            ConflictPolicy::Merge,
+            false,
+            &existing,
+            &existing_salt,
+        )
+        .unwrap();
+
+        assert_eq!(report[0].action, ImportAction::Merged);
+        let merged: serde_json::Value =
+            serde_json::from_str(existing.borrow().get("accounts").unwrap()).unwrap();
+        assert_eq!(merged["user"], "bob");
+        assert_eq!(merged["extra"], "new");
+    }
+
+    #[test]
+    fn merge_policy_rejects_encrypted_entries() {
+        let dir = temp_dir("merge-encrypted-1");
+        seed_real_vault(
+            &dir,
+            "master-password",
+            &[("accounts", "{\"user\":\"alice\"}"), ("notes", "{\"user\":\"carol\"}")],
+        );
+        let archive = export_vault(&dir, "pass").unwrap();
+
+        // Existing vault is already unlocked/encrypted under the *same*
+        // salt, matching the normal steady-state case once chunk0-4's
+        // encryption-at-rest has run at least once, so this test isolates
+        // the entries-merge rejection from the salt-merge rejection.
+        let existing_envelope = fs::read_to_string(dir.join("notes.json")).unwrap();
+        let existing = RefCell::new(HashMap::from([("accounts".to_string(), existing_envelope)]));
+        let existing_salt = RefCell::new(Some(fs::read(dir.join(crypto::VAULT_SALT_FILE)).unwrap()));
+
+        let result = import_with_fs(
+            &archive,
+            "pass",
+            ConflictPolicy::Merge,
+            false,
+            &existing,
+            &existing_salt,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejected_merge_leaves_earlier_valid_entries_unwritten() {
+        // "aaa" sorts before "zzz" in the archive's BTreeMap, so a naive
+        // single-pass import would merge "aaa" to disk before ever reaching
+        // "zzz" and discovering it can't be merged. Import must validate the
+        // whole archive up front instead, so neither entry is written.
+        let dir = temp_dir("partial-write-1");
+        fs::write(dir.join("aaa.json"), "{\"user\":\"alice\",\"extra\":\"new\"}").unwrap();
+        seed_real_vault(&dir, "master-password", &[("zzz", "{\"user\":\"carol\"}")]);
+        let archive = export_vault(&dir, "pass").unwrap();
+
+        let existing = RefCell::new(HashMap::from([
+            ("aaa".to_string(), "{\"user\":\"bob\"}".to_string()),
+            (
+                "zzz".to_string(),
+                fs::read_to_string(dir.join("zzz.json")).unwrap(),
+            ),
+        ]));
+        let existing_salt = RefCell::new(None);
+
+        let result = import_with_fs(
+            &archive,
+            "pass",
+            ConflictPolicy::Merge,
+            false,
+            &existing,
+            &existing_salt,
+        );
+
+        assert!(result.is_err());
+        // Neither entry should have been touched: "aaa" validated cleanly
+        // but must not be written just because "zzz" failed validation later.
+        assert_eq!(existing.borrow().get("aaa").unwrap(), "{\"user\":\"bob\"}");
+    }
+
+    #[test]
+    fn dry_run_reports_without_writing() {
+        let dir = temp_dir("dry-run-1");
+        seed_real_vault(&dir, "master-password", &[("accounts", "{\"user\":\"alice\"}")]);
+        let archive = export_vault(&dir, "pass").unwrap();
+
+        let existing = RefCell::new(HashMap::new());
+        let existing_salt = RefCell::new(None);
+
+        let report = import_with_fs(
+            &archive,
+            "pass",
+            ConflictPolicy::Overwrite,
+            true,
+            &existing,
+            &existing_salt,
+        )
+        .unwrap();
+
+        assert!(existing.borrow().is_empty());
+        assert!(existing_salt.borrow().is_none());
+        assert!(report.iter().all(|r| r.action == ImportAction::Written));
+    }
+}
@@ -0,0 +1,195 @@
+//! Encryption primitives backing the cipher vault: Argon2id key derivation
+//! and XChaCha20-Poly1305 AEAD encryption of stored values. Kept separate
+//! from `storage.rs` so the Tauri command handlers stay focused on path and
+//! file handling while this module owns the cryptographic format.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+pub const VAULT_MAGIC: &str = "CGVAULT";
+pub const VAULT_VERSION: u8 = 1;
+pub const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Name of the file (deliberately not `*.json`, so it's skipped by storage
+/// key lookups and plaintext migration) holding the vault's Argon2 salt.
+pub const VAULT_SALT_FILE: &str = ".vault-salt";
+
+/// A derived 256-bit vault key. Zeroized on drop so `lock()` reliably wipes
+/// key material from memory rather than relying on the allocator.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct VaultKey([u8; KEY_LEN]);
+
+/// On-disk representation of an encrypted value. `salt` is duplicated into
+/// every entry (even though it's the same salt for the whole vault) so that
+/// any single file is self-describing and can be decrypted given only the
+/// master password, without depending on a separate salt file surviving.
+#[derive(Serialize, Deserialize)]
+struct EncryptedEnvelope {
+    magic: String,
+    version: u8,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Derives a 256-bit key from `password` and `salt` using Argon2id with the
+/// library's recommended default parameters.
+pub fn derive_key(password: &str, salt: &[u8; SALT_LEN]) -> Result<VaultKey, String> {
+    let mut key_bytes = [0u8; KEY_LEN];
+    argon2::Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key_bytes)
+        .map_err(|e| format!("key derivation failed: {}", e))?;
+    Ok(VaultKey(key_bytes))
+}
+
+/// Generates a fresh random vault salt.
+pub fn random_salt() -> [u8; SALT_LEN] {
+    use rand::RngCore;
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// Encrypts `plaintext` under `key` with a fresh random nonce, returning the
+/// serialized, self-describing envelope to write to disk.
+pub fn encrypt(key: &VaultKey, salt: &[u8; SALT_LEN], plaintext: &[u8]) -> Result<String, String> {
+    let cipher = XChaCha20Poly1305::new(key.0.as_ref().into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| format!("encryption failed: {}", e))?;
+
+    let envelope = EncryptedEnvelope {
+        magic: VAULT_MAGIC.to_string(),
+        version: VAULT_VERSION,
+        salt: base64::encode(salt),
+        nonce: base64::encode(nonce),
+        ciphertext: base64::encode(ciphertext),
+    };
+
+    serde_json::to_string(&envelope).map_err(|e| e.to_string())
+}
+
+/// Decrypts an envelope produced by [`encrypt`]. Returns an error if the MAC
+/// check fails (wrong password or tampering) or the envelope is malformed.
+pub fn decrypt(key: &VaultKey, data: &str) -> Result<Vec<u8>, String> {
+    let envelope: EncryptedEnvelope =
+        serde_json::from_str(data).map_err(|_| "not a valid encrypted vault entry".to_string())?;
+
+    if envelope.magic != VAULT_MAGIC {
+        return Err("unrecognized vault file format".to_string());
+    }
+    if envelope.version != VAULT_VERSION {
+        return Err(format!(
+            "unsupported vault format version {}",
+            envelope.version
+        ));
+    }
+
+    let nonce_bytes = base64::decode(&envelope.nonce).map_err(|e| e.to_string())?;
+    if nonce_bytes.len() != XNonce::default().len() {
+        return Err("encrypted vault entry has a malformed nonce".to_string());
+    }
+    let nonce = XNonce::from_slice(&nonce_bytes);
+    let ciphertext = base64::decode(&envelope.ciphertext).map_err(|e| e.to_string())?;
+
+    let cipher = XChaCha20Poly1305::new(key.0.as_ref().into());
+    cipher
+        .decrypt(nonce, ciphertext.as_ref())
+        .map_err(|_| "authentication failed: wrong password or tampered vault file".to_string())
+}
+
+/// Derives a fresh random-salt key from `passphrase` and encrypts
+/// `plaintext` with it, for one-off passphrase-based encryption (e.g. backup
+/// archives) rather than the long-lived vault session key.
+pub fn encrypt_with_passphrase(passphrase: &str, plaintext: &[u8]) -> Result<String, String> {
+    let salt = random_salt();
+    let key = derive_key(passphrase, &salt)?;
+    encrypt(&key, &salt, plaintext)
+}
+
+/// Inverse of [`encrypt_with_passphrase`]: reads the salt back out of the
+/// envelope, re-derives the key from `passphrase`, and decrypts.
+pub fn decrypt_with_passphrase(passphrase: &str, data: &str) -> Result<Vec<u8>, String> {
+    let envelope: EncryptedEnvelope =
+        serde_json::from_str(data).map_err(|_| "not a valid encrypted archive".to_string())?;
+
+    let salt_bytes = base64::decode(&envelope.salt).map_err(|e| e.to_string())?;
+    if salt_bytes.len() != SALT_LEN {
+        return Err("encrypted archive has a malformed salt".to_string());
+    }
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&salt_bytes);
+
+    let key = derive_key(passphrase, &salt)?;
+    decrypt(&key, data)
+}
+
+/// Whether `data` looks like an [`encrypt`]-produced envelope, as opposed to
+/// a legacy plaintext vault entry.
+pub fn is_encrypted(data: &str) -> bool {
+    serde_json::from_str::<EncryptedEnvelope>(data)
+        .map(|envelope| envelope.magic == VAULT_MAGIC)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_then_decrypt_round_trips() {
+        let salt = random_salt();
+        let key = derive_key("hunter2", &salt).unwrap();
+
+        let envelope = encrypt(&key, &salt, b"super secret value").unwrap();
+        assert!(is_encrypted(&envelope));
+
+        let plaintext = decrypt(&key, &envelope).unwrap();
+        assert_eq!(plaintext, b"super secret value");
+    }
+
+    #[test]
+    fn decrypt_fails_with_wrong_password() {
+        let salt = random_salt();
+        let key = derive_key("correct-password", &salt).unwrap();
+        let wrong_key = derive_key("wrong-password", &salt).unwrap();
+
+        let envelope = encrypt(&key, &salt, b"secret").unwrap();
+        assert!(decrypt(&wrong_key, &envelope).is_err());
+    }
+
+    #[test]
+    fn decrypt_fails_on_tampered_ciphertext() {
+        let salt = random_salt();
+        let key = derive_key("hunter2", &salt).unwrap();
+        let envelope = encrypt(&key, &salt, b"secret").unwrap();
+
+        let mut tampered: serde_json::Value = serde_json::from_str(&envelope).unwrap();
+        tampered["ciphertext"] = serde_json::Value::String(base64::encode(b"not the real ciphertext"));
+
+        assert!(decrypt(&key, &tampered.to_string()).is_err());
+    }
+
+    #[test]
+    fn plaintext_is_not_recognized_as_encrypted() {
+        assert!(!is_encrypted("{\"username\":\"alice\"}"));
+    }
+
+    #[test]
+    fn passphrase_round_trip() {
+        let envelope = encrypt_with_passphrase("correct horse battery staple", b"archive bytes").unwrap();
+        let plaintext = decrypt_with_passphrase("correct horse battery staple", &envelope).unwrap();
+        assert_eq!(plaintext, b"archive bytes");
+    }
+
+    #[test]
+    fn passphrase_round_trip_fails_with_wrong_passphrase() {
+        let envelope = encrypt_with_passphrase("correct horse battery staple", b"archive bytes").unwrap();
+        assert!(decrypt_with_passphrase("wrong passphrase", &envelope).is_err());
+    }
+}
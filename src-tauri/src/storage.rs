@@ -1,34 +1,288 @@
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Manager, State};
 use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::backup::{self, ConflictPolicy, ImportEntryReport};
+use crate::crypto::{self, VaultKey, SALT_LEN, VAULT_SALT_FILE};
+
+/// An unlocked vault session: the derived key plus the salt it was derived
+/// from, so newly-written entries can embed the same salt. Dropping this
+/// (e.g. on `lock()`) zeroizes the key via `VaultKey`'s `ZeroizeOnDrop`.
+struct VaultSession {
+    key: VaultKey,
+    salt: [u8; SALT_LEN],
+}
+
+/// Tauri-managed state holding the current vault session, if unlocked.
+#[derive(Default)]
+pub struct VaultState(Mutex<Option<VaultSession>>);
+
+/// Setting this environment variable to any value disables the group/other
+/// writable permission check on read, for CI or containers that run as root
+/// under an unusual umask where ownership checks don't make sense.
+const SKIP_PERMISSION_CHECK_ENV: &str = "CIPHERGUARD_SKIP_PERMISSION_CHECK";
+
+/// Characters that are never allowed in a storage key, either because they are
+/// path separators or because they are reserved by one of the filesystems we
+/// run on.
+const FORBIDDEN_CHARS: &[char] = &['.', ':', '<', '>', '"', '/', '\\', '|', '?', '*'];
+
+const RESERVED_WINDOWS_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Rejects keys that could be used to escape `app_data_dir` or that collide
+/// with reserved filenames, rather than trying to rewrite them.
+fn sanitize_key(key: &str) -> Result<String, String> {
+    if key.is_empty() {
+        return Err("storage key must not be empty".to_string());
+    }
+
+    if key.contains("..") {
+        return Err(format!("storage key '{}' must not contain '..'", key));
+    }
+
+    if key.chars().any(|c| FORBIDDEN_CHARS.contains(&c)) {
+        return Err(format!(
+            "storage key '{}' contains a forbidden character",
+            key
+        ));
+    }
+
+    if RESERVED_WINDOWS_NAMES.contains(&key.to_uppercase().as_str()) {
+        return Err(format!("storage key '{}' is a reserved name", key));
+    }
+
+    Ok(key.to_string())
+}
+
+/// Builds the on-disk path for `key` inside `app_data_dir`, rejecting keys
+/// that fail sanitization and, as defense-in-depth, verifying that the
+/// resolved path is still a direct child of `app_data_dir` once canonicalized.
+fn safe_storage_path(app_data_dir: &Path, key: &str) -> Result<PathBuf, String> {
+    let sanitized = sanitize_key(key)?;
+    let file_path = app_data_dir.join(format!("{}.json", sanitized));
+
+    let canonical_dir = app_data_dir
+        .canonicalize()
+        .map_err(|e| format!("failed to canonicalize app data dir: {}", e))?;
+
+    // The file may not exist yet (e.g. a fresh write), so canonicalize the
+    // parent and re-attach the file name instead of canonicalizing the file
+    // path itself.
+    let canonical_parent = file_path
+        .parent()
+        .ok_or_else(|| "storage path has no parent directory".to_string())?
+        .canonicalize()
+        .map_err(|e| format!("failed to canonicalize storage path: {}", e))?;
+
+    if canonical_parent != canonical_dir {
+        return Err(format!(
+            "storage key '{}' resolves outside of the app data directory",
+            key
+        ));
+    }
+
+    let file_name = file_path
+        .file_name()
+        .ok_or_else(|| "storage path has no file name".to_string())?;
+
+    Ok(canonical_parent.join(file_name))
+}
 
 #[tauri::command]
-pub fn read_storage(key: String, app: AppHandle) -> Result<String, String> {
+pub fn read_storage(
+    key: String,
+    app: AppHandle,
+    vault: State<VaultState>,
+) -> Result<String, String> {
     let app_data_dir = app.path().app_data_dir()
         .map_err(|e| e.to_string())?;
 
-    let file_path = app_data_dir.join(format!("{}.json", key));
+    // Nothing has been written yet, so there's no directory to canonicalize;
+    // treat this the same as a missing key.
+    if !app_data_dir.exists() {
+        return Ok(String::from("null"));
+    }
+
+    let file_path = safe_storage_path(&app_data_dir, &key)?;
 
     if !file_path.exists() {
         return Ok(String::from("null"));
     }
 
-    fs::read_to_string(file_path)
-        .map_err(|e| e.to_string())
+    check_vault_permissions(&file_path, &app_data_dir)?;
+
+    let contents = fs::read_to_string(file_path)
+        .map_err(|e| e.to_string())?;
+
+    if !crypto::is_encrypted(&contents) {
+        return Ok(contents);
+    }
+
+    let guard = vault.0.lock().map_err(|_| "vault state poisoned".to_string())?;
+    let session = guard
+        .as_ref()
+        .ok_or_else(|| "vault is locked; call unlock first".to_string())?;
+
+    let plaintext = crypto::decrypt(&session.key, &contents)?;
+    String::from_utf8(plaintext).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn write_storage(key: String, value: String, app: AppHandle) -> Result<(), String> {
+pub fn write_storage(
+    key: String,
+    value: String,
+    app: AppHandle,
+    vault: State<VaultState>,
+) -> Result<(), String> {
     let app_data_dir = app.path().app_data_dir()
         .map_err(|e| e.to_string())?;
 
     // Create directory if it doesn't exist
     fs::create_dir_all(&app_data_dir)
         .map_err(|e| e.to_string())?;
+    restrict_to_owner(&app_data_dir, 0o700)?;
+
+    let file_path = safe_storage_path(&app_data_dir, &key)?;
+
+    let guard = vault.0.lock().map_err(|_| "vault state poisoned".to_string())?;
+    let session = guard
+        .as_ref()
+        .ok_or_else(|| "vault is locked; call unlock first".to_string())?;
+
+    let envelope = crypto::encrypt(&session.key, &session.salt, value.as_bytes())?;
+    atomic_write(&file_path, envelope.as_bytes())
+}
+
+/// Derives the vault key from `master_password` (creating a fresh salt on
+/// first unlock) and holds it in memory for the session. Any existing
+/// plaintext `*.json` entries are transparently migrated to the encrypted
+/// format as part of unlocking.
+#[tauri::command]
+pub fn unlock(
+    master_password: String,
+    app: AppHandle,
+    vault: State<VaultState>,
+) -> Result<(), String> {
+    let app_data_dir = app.path().app_data_dir()
+        .map_err(|e| e.to_string())?;
+
+    fs::create_dir_all(&app_data_dir)
+        .map_err(|e| e.to_string())?;
+    restrict_to_owner(&app_data_dir, 0o700)?;
+
+    let salt_path = app_data_dir.join(VAULT_SALT_FILE);
+    let salt = if salt_path.exists() {
+        let raw = fs::read(&salt_path).map_err(|e| e.to_string())?;
+        let mut salt = [0u8; SALT_LEN];
+        if raw.len() != SALT_LEN {
+            return Err("vault salt file is corrupt".to_string());
+        }
+        salt.copy_from_slice(&raw);
+        salt
+    } else {
+        let salt = crypto::random_salt();
+        atomic_write(&salt_path, &salt)?;
+        salt
+    };
+
+    let key = crypto::derive_key(&master_password, &salt)?;
+    let session = VaultSession { key, salt };
+
+    migrate_plaintext_entries(&app_data_dir, &session)?;
+
+    *vault.0.lock().map_err(|_| "vault state poisoned".to_string())? = Some(session);
+    Ok(())
+}
+
+/// Wipes the derived key from memory, requiring `unlock` again before any
+/// further reads or writes.
+#[tauri::command]
+pub fn lock(vault: State<VaultState>) -> Result<(), String> {
+    *vault.0.lock().map_err(|_| "vault state poisoned".to_string())? = None;
+    Ok(())
+}
+
+/// Bundles every stored entry into a single encrypted backup blob, suitable
+/// for copying off-device. Independent of whether the vault is currently
+/// unlocked, since it's encrypted under its own passphrase rather than the
+/// session key.
+#[tauri::command]
+pub fn export_vault(passphrase: String, app: AppHandle) -> Result<String, String> {
+    let app_data_dir = app.path().app_data_dir()
+        .map_err(|e| e.to_string())?;
+
+    backup::export_vault(&app_data_dir, &passphrase)
+}
+
+/// Restores entries (and, if present, the vault's Argon2 salt) from a backup
+/// produced by [`export_vault`]. `conflict_policy` is one of `"overwrite"`,
+/// `"skip-existing"`, or `"merge"`; `dry_run` reports what would change
+/// without touching disk.
+#[tauri::command]
+pub fn import_vault(
+    data: String,
+    passphrase: String,
+    conflict_policy: String,
+    dry_run: bool,
+    app: AppHandle,
+) -> Result<Vec<ImportEntryReport>, String> {
+    let app_data_dir = app.path().app_data_dir()
+        .map_err(|e| e.to_string())?;
+    fs::create_dir_all(&app_data_dir)
+        .map_err(|e| e.to_string())?;
+    restrict_to_owner(&app_data_dir, 0o700)?;
 
-    let file_path = app_data_dir.join(format!("{}.json", key));
+    let policy = ConflictPolicy::from_str(&conflict_policy)?;
 
-    fs::write(file_path, value)
-        .map_err(|e| e.to_string())
+    backup::import_vault(
+        &data,
+        &passphrase,
+        policy,
+        dry_run,
+        |key| {
+            let path = safe_storage_path(&app_data_dir, key).ok()?;
+            fs::read_to_string(path).ok()
+        },
+        |key, contents| {
+            let path = safe_storage_path(&app_data_dir, key)?;
+            atomic_write(&path, contents.as_bytes())
+        },
+        || fs::read(app_data_dir.join(VAULT_SALT_FILE)).ok(),
+        |salt| atomic_write(&app_data_dir.join(VAULT_SALT_FILE), salt),
+    )
+}
+
+/// Re-encrypts any legacy plaintext `*.json` entries in `app_data_dir` under
+/// the freshly unlocked session, so old vaults created before encryption was
+/// added keep working without a separate migration step.
+fn migrate_plaintext_entries(app_data_dir: &Path, session: &VaultSession) -> Result<(), String> {
+    let entries = fs::read_dir(app_data_dir).map_err(|e| e.to_string())?;
+
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        if crypto::is_encrypted(&contents) {
+            continue;
+        }
+
+        let envelope = crypto::encrypt(&session.key, &session.salt, contents.as_bytes())?;
+        atomic_write(&path, envelope.as_bytes())?;
+    }
+
+    Ok(())
 }
 
 #[tauri::command]
@@ -36,7 +290,11 @@ pub fn remove_storage(key: String, app: AppHandle) -> Result<(), String> {
     let app_data_dir = app.path().app_data_dir()
         .map_err(|e| e.to_string())?;
 
-    let file_path = app_data_dir.join(format!("{}.json", key));
+    if !app_data_dir.exists() {
+        return Ok(());
+    }
+
+    let file_path = safe_storage_path(&app_data_dir, &key)?;
 
     if file_path.exists() {
         fs::remove_file(file_path)
@@ -45,3 +303,317 @@ pub fn remove_storage(key: String, app: AppHandle) -> Result<(), String> {
 
     Ok(())
 }
+
+/// Writes `contents` to `path` crash-safely: the data lands in a sibling
+/// temp file first, is flushed and fsynced, and only then is renamed over
+/// `path`. A crash or power loss mid-write can therefore never leave `path`
+/// truncated or empty. The temp file is cleaned up on any error.
+fn atomic_write(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| "storage path has no parent directory".to_string())?;
+
+    let counter = {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    };
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| "storage path has no file name".to_string())?
+        .to_string_lossy();
+    let tmp_path = dir.join(format!("{}.tmp-{}-{}", file_name, nanos, counter));
+
+    let result = (|| -> Result<(), String> {
+        let mut tmp_file = fs::File::create(&tmp_path)
+            .map_err(|e| format!("failed to create temp file: {}", e))?;
+        restrict_to_owner(&tmp_path, 0o600)?;
+
+        tmp_file
+            .write_all(contents)
+            .map_err(|e| format!("failed to write temp file: {}", e))?;
+        tmp_file
+            .flush()
+            .map_err(|e| format!("failed to flush temp file: {}", e))?;
+        tmp_file
+            .sync_all()
+            .map_err(|e| format!("failed to fsync temp file: {}", e))?;
+        drop(tmp_file);
+
+        fs::rename(&tmp_path, path)
+            .map_err(|e| format!("failed to rename temp file into place: {}", e))?;
+
+        fsync_dir(dir)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// Fsyncs a directory so a preceding rename inside it is durable across a
+/// crash. No-op on Windows, which has no equivalent operation.
+#[cfg(unix)]
+fn fsync_dir(dir: &Path) -> Result<(), String> {
+    fs::File::open(dir)
+        .and_then(|d| d.sync_all())
+        .map_err(|e| format!("failed to fsync directory {}: {}", dir.display(), e))
+}
+
+#[cfg(not(unix))]
+fn fsync_dir(_dir: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+/// Restricts `path` to the given Unix mode (owner-only). No-op on Windows,
+/// where the app data directory is already protected by the user's ACL.
+#[cfg(unix)]
+fn restrict_to_owner(path: &Path, mode: u32) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .map_err(|e| format!("failed to set permissions on {}: {}", path.display(), e))
+}
+
+#[cfg(not(unix))]
+fn restrict_to_owner(_path: &Path, _mode: u32) -> Result<(), String> {
+    Ok(())
+}
+
+/// Defense-in-depth check that a vault file (and its parent directory)
+/// haven't been loosened to be group/other-writable, which would let another
+/// local user tamper with it undetected. Skipped entirely when
+/// `CIPHERGUARD_SKIP_PERMISSION_CHECK` is set, for CI/containers running as
+/// root under an unusual umask.
+#[cfg(unix)]
+fn check_vault_permissions(file_path: &Path, app_data_dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::MetadataExt;
+
+    if std::env::var_os(SKIP_PERMISSION_CHECK_ENV).is_some() {
+        return Ok(());
+    }
+
+    let current_uid = unsafe { libc::geteuid() };
+
+    for path in [file_path, app_data_dir] {
+        let metadata = fs::metadata(path)
+            .map_err(|e| format!("failed to stat {}: {}", path.display(), e))?;
+
+        if metadata.uid() != current_uid {
+            return Err(format!(
+                "{} is not owned by the current user; refusing to trust it",
+                path.display()
+            ));
+        }
+
+        if metadata.mode() & 0o022 != 0 {
+            return Err(format!(
+                "{} is group/other-writable; refusing to trust it",
+                path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_vault_permissions(_file_path: &Path, _app_data_dir: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cipherguard-storage-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rejects_parent_directory_traversal() {
+        let dir = temp_dir("traversal-1");
+        assert!(safe_storage_path(&dir, "../../secrets").is_err());
+    }
+
+    #[test]
+    fn rejects_backslash_traversal() {
+        let dir = temp_dir("traversal-2");
+        assert!(safe_storage_path(&dir, "..\\config").is_err());
+    }
+
+    #[test]
+    fn rejects_forward_slash_in_key() {
+        let dir = temp_dir("traversal-3");
+        assert!(safe_storage_path(&dir, "nested/key").is_err());
+    }
+
+    #[test]
+    fn rejects_reserved_windows_names() {
+        let dir = temp_dir("reserved-1");
+        assert!(safe_storage_path(&dir, "CON").is_err());
+        assert!(safe_storage_path(&dir, "con").is_err());
+        assert!(safe_storage_path(&dir, "LPT1").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_key() {
+        let dir = temp_dir("empty-1");
+        assert!(safe_storage_path(&dir, "").is_err());
+    }
+
+    #[test]
+    fn accepts_a_normal_key() {
+        let dir = temp_dir("normal-1");
+        let path = safe_storage_path(&dir, "accounts").unwrap();
+        assert_eq!(path, dir.canonicalize().unwrap().join("accounts.json"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restrict_to_owner_sets_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("perms-1");
+        let file = dir.join("vault.json");
+        fs::write(&file, "{}").unwrap();
+
+        restrict_to_owner(&file, 0o600).unwrap();
+        let mode = fs::metadata(&file).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_vault_permissions_rejects_group_writable_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("perms-2");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        let file = dir.join("vault.json");
+        fs::write(&file, "{}").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o666)).unwrap();
+
+        assert!(check_vault_permissions(&file, &dir).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_vault_permissions_accepts_owner_only_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("perms-3");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        let file = dir.join("vault.json");
+        fs::write(&file, "{}").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        assert!(check_vault_permissions(&file, &dir).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_vault_permissions_respects_skip_env_var() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("perms-4");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        let file = dir.join("vault.json");
+        fs::write(&file, "{}").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o666)).unwrap();
+
+        std::env::set_var(SKIP_PERMISSION_CHECK_ENV, "1");
+        let result = check_vault_permissions(&file, &dir);
+        std::env::remove_var(SKIP_PERMISSION_CHECK_ENV);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn atomic_write_produces_expected_contents() {
+        let dir = temp_dir("atomic-1");
+        let file = dir.join("vault.json");
+
+        atomic_write(&file, b"{\"a\":1}").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "{\"a\":1}");
+    }
+
+    #[test]
+    fn atomic_write_leaves_original_intact_on_failure() {
+        let dir = temp_dir("atomic-2");
+        let file = dir.join("vault.json");
+        fs::write(&file, "original").unwrap();
+
+        // A target whose parent directory doesn't exist makes creating the
+        // temp file fail, so the write errors out before the rename into
+        // place ever happens.
+        let bogus = dir.join("missing-subdir").join("vault.json");
+        let result = atomic_write(&bogus, b"new contents");
+        assert!(result.is_err());
+
+        // The real target file, untouched by this failed write, must survive.
+        assert_eq!(fs::read_to_string(&file).unwrap(), "original");
+
+        let leftover_tmp_files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().contains(".tmp-"))
+            .collect();
+        assert!(leftover_tmp_files.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_write_sets_owner_only_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("atomic-3");
+        let file = dir.join("vault.json");
+
+        atomic_write(&file, b"{}").unwrap();
+        let mode = fs::metadata(&file).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[test]
+    fn migrate_plaintext_entries_encrypts_legacy_files_in_place() {
+        let dir = temp_dir("migrate-1");
+        fs::write(dir.join("accounts.json"), "{\"user\":\"alice\"}").unwrap();
+        fs::write(dir.join(VAULT_SALT_FILE), [0u8; SALT_LEN]).unwrap();
+
+        let salt = crypto::random_salt();
+        let key = crypto::derive_key("hunter2", &salt).unwrap();
+        let session = VaultSession { key, salt };
+
+        migrate_plaintext_entries(&dir, &session).unwrap();
+
+        let migrated = fs::read_to_string(dir.join("accounts.json")).unwrap();
+        assert!(crypto::is_encrypted(&migrated));
+        let plaintext = crypto::decrypt(&session.key, &migrated).unwrap();
+        assert_eq!(plaintext, b"{\"user\":\"alice\"}");
+    }
+
+    #[test]
+    fn migrate_plaintext_entries_skips_already_encrypted_files() {
+        let dir = temp_dir("migrate-2");
+        let salt = crypto::random_salt();
+        let key = crypto::derive_key("hunter2", &salt).unwrap();
+        let session = VaultSession { key, salt };
+
+        let envelope = crypto::encrypt(&session.key, &session.salt, b"already encrypted").unwrap();
+        fs::write(dir.join("accounts.json"), &envelope).unwrap();
+
+        migrate_plaintext_entries(&dir, &session).unwrap();
+
+        let after = fs::read_to_string(dir.join("accounts.json")).unwrap();
+        assert_eq!(after, envelope);
+    }
+}